@@ -1,8 +1,14 @@
 use crate::cli::{ObserverWardConfig, UnixSocketAddr};
 use crate::helper::Helper;
+use crate::jobs::{self, JobQueue};
+use crate::metrics;
 use crate::output::Output;
+use crate::result_store::{ResultFilter, ResultStore};
+use crate::token::AccessScope;
 use crate::{MatchedResult, ObserverWard, cluster_templates};
-use actix_web::{App, HttpResponse, HttpServer, Responder, get, middleware, post, rt, web};
+use actix_web::{
+  App, HttpRequest, HttpResponse, HttpServer, Responder, get, middleware, post, rt, web,
+};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use console::Emoji;
 #[cfg(not(target_os = "windows"))]
@@ -11,20 +17,44 @@ use engine::execute::ClusterType;
 use futures::StreamExt;
 use futures::channel::mpsc::unbounded;
 use log::{error, info};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 
 #[derive(Clone, Debug)]
 struct TokenAuth {
-  token: Option<String>,
+  // 令牌 -> 该令牌被授予的能力范围，None表示未配置鉴权，放行所有请求
+  tokens: Option<HashMap<String, Vec<AccessScope>>>,
 }
 
-fn validator(token_auth: web::Data<TokenAuth>, credentials: BearerAuth) -> bool {
-  if let Some(token) = &token_auth.token {
-    token == credentials.token()
-  } else {
-    true
+/// 鉴权失败的两种情形：令牌本身不认识 vs 令牌认识但能力范围不够
+enum AuthError {
+  UnknownToken,
+  MissingScope,
+}
+
+impl AuthError {
+  fn response(&self) -> HttpResponse {
+    match self {
+      AuthError::UnknownToken => HttpResponse::Unauthorized().finish(),
+      AuthError::MissingScope => HttpResponse::Forbidden().finish(),
+    }
+  }
+}
+
+fn validator(
+  token_auth: &web::Data<TokenAuth>,
+  credentials: &BearerAuth,
+  scope: AccessScope,
+) -> Result<(), AuthError> {
+  match &token_auth.tokens {
+    Some(tokens) => match tokens.get(credentials.token()) {
+      Some(scopes) if scopes.contains(&scope) => Ok(()),
+      Some(_) => Err(AuthError::MissingScope),
+      None => Err(AuthError::UnknownToken),
+    },
+    None => Ok(()),
   }
 }
 
@@ -35,9 +65,10 @@ async fn what_web_api(
   config: web::Json<ObserverWardConfig>,
   cli_config: web::Data<ObserverWardConfig>,
   cl: web::Data<RwLock<ClusterType>>,
+  store: web::Data<Option<Arc<dyn ResultStore>>>,
 ) -> impl Responder {
-  if !validator(token, auth) {
-    return HttpResponse::Unauthorized().finish();
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return err.response();
   }
   let mut config = config.clone();
   if config.plugin.is_some(){
@@ -60,23 +91,105 @@ async fn what_web_api(
   tokio::task::spawn(async move {
     ObserverWard::new(&config, cl).execute(tx).await;
   });
+  let start = Instant::now();
   if webhook {
     // 异步识别任务，通过webhook返回结果
     rt::spawn(async move {
       while let Some(execute_result) = rx.next().await {
+        metrics::TARGETS_SCANNED.inc();
+        for name in execute_result.matched.keys() {
+          metrics::MATCHES_BY_FINGERPRINT.with_label_values(&[name]).inc();
+        }
+        if let Some(store) = store.as_ref() {
+          if let Err(err) = store.save(&execute_result.target, &execute_result.matched).await {
+            error!("{}save result err: {}", Emoji("💢", ""), err);
+          }
+        }
         output.webhook_results(vec![execute_result.matched]).await;
       }
+      metrics::SCAN_DURATION_SECONDS.observe(start.elapsed().as_secs_f64());
     });
     HttpResponse::Ok().finish()
   } else {
     let mut results: Vec<BTreeMap<String, MatchedResult>> = Vec::new();
     while let Some(execute_result) = rx.next().await {
+      metrics::TARGETS_SCANNED.inc();
+      for name in execute_result.matched.keys() {
+        metrics::MATCHES_BY_FINGERPRINT.with_label_values(&[name]).inc();
+      }
+      if let Some(store) = store.as_ref() {
+        if let Err(err) = store.save(&execute_result.target, &execute_result.matched).await {
+          error!("{}save result err: {}", Emoji("💢", ""), err);
+        }
+      }
       results.push(execute_result.matched)
     }
+    metrics::SCAN_DURATION_SECONDS.observe(start.elapsed().as_secs_f64());
     HttpResponse::Ok().json(results)
   }
 }
 
+// 通过WebSocket推送扫描结果，每扫完一个目标就推送一条（没有命中时为空对象），扫描结束后关闭连接
+#[get("/v1/observer_ward/ws")]
+async fn what_web_ws_api(
+  req: HttpRequest,
+  body: web::Payload,
+  token: web::Data<TokenAuth>,
+  auth: BearerAuth,
+  cli_config: web::Data<ObserverWardConfig>,
+  cl: web::Data<RwLock<ClusterType>>,
+) -> actix_web::Result<HttpResponse> {
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return Ok(err.response());
+  }
+  let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body)?;
+  let cli_config = cli_config.get_ref().clone();
+  let cl = {
+    if let Ok(cl_guard) = cl.read() {
+      cl_guard.deref().clone()
+    } else {
+      ClusterType::default()
+    }
+  };
+  rt::spawn(async move {
+    // 第一帧文本消息携带本次扫描的配置，和POST /v1/observer_ward保持一致
+    let mut config = cli_config.clone();
+    while let Some(Ok(msg)) = msg_stream.next().await {
+      if let actix_ws::Message::Text(text) = msg {
+        if let Ok(user_config) = serde_json::from_str::<ObserverWardConfig>(&text) {
+          config = user_config;
+          if config.plugin.is_some() {
+            config.plugin = cli_config.plugin.clone();
+          }
+          config.config_dir = cli_config.config_dir.clone();
+          config.mode = cli_config.mode.clone();
+          config.proxy = cli_config.proxy.clone();
+          config.nuclei_args = cli_config.nuclei_args.clone();
+        }
+        break;
+      }
+    }
+    let (tx, mut rx) = unbounded();
+    tokio::task::spawn(async move {
+      ObserverWard::new(&config, cl).execute(tx).await;
+    });
+    while let Some(execute_result) = rx.next().await {
+      match serde_json::to_string(&execute_result.matched) {
+        Ok(text) => {
+          if session.text(text).await.is_err() {
+            break;
+          }
+        }
+        Err(err) => {
+          error!("{}serialize result err: {}", Emoji("💢", ""), err);
+        }
+      }
+    }
+    session.close(None).await.unwrap_or_default();
+  });
+  Ok(response)
+}
+
 #[post("/v1/config")]
 async fn set_config_api(
   token: web::Data<TokenAuth>,
@@ -85,8 +198,8 @@ async fn set_config_api(
   cl: web::Data<RwLock<ClusterType>>,
   cli_config: web::Data<ObserverWardConfig>,
 ) -> impl Responder {
-  if !validator(token, auth) {
-    return HttpResponse::Unauthorized().finish();
+  if let Err(err) = validator(&token, &auth, AccessScope::WriteConfig) {
+    return err.response();
   }
   // 创建一个可修改的副本，并继承服务端的配置目录等字段
   let mut cfg = config.clone();
@@ -98,6 +211,13 @@ async fn set_config_api(
   cfg.mode = cli_config.mode.clone();
   cfg.proxy = cli_config.proxy.clone();
   cfg.nuclei_args = cli_config.nuclei_args.clone();
+  // 下载指纹/插件需要额外的update权限，避免write_config持有者顺带拿到出网更新的能力
+  let wants_update = cfg.update_fingerprint || cfg.update_service_fingerprint || cfg.update_plugin;
+  if wants_update {
+    if let Err(err) = validator(&token, &auth, AccessScope::Update) {
+      return err.response();
+    }
+  }
   let helper = Helper::new(&cfg);
   if cfg.update_fingerprint {
       helper.update_fingerprint().await;
@@ -112,6 +232,7 @@ async fn set_config_api(
   if let Ok(mut cl_guard) = cl.write() {
       let templates = cfg.templates();
       let new_cl = cluster_templates(&templates);
+      metrics::OPTIMIZED_PROBES.set(new_cl.count() as i64);
       *cl_guard = new_cl;
   }
   HttpResponse::Ok().json(cfg)
@@ -123,10 +244,113 @@ async fn get_config_api(
   auth: BearerAuth,
   config: web::Data<ObserverWardConfig>,
 ) -> impl Responder {
-  if !validator(token, auth) {
-    return HttpResponse::Unauthorized().finish();
+  if let Err(err) = validator(&token, &auth, AccessScope::ReadConfig) {
+    return err.response();
   }
-  HttpResponse::Ok().json(config.clone())
+  // read_config权限本身不该带出其它令牌的密钥和权限范围，否则低权限令牌也能提权
+  let mut cfg = config.clone();
+  cfg.tokens = None;
+  HttpResponse::Ok().json(cfg)
+}
+
+// Prometheus文本格式的指标，方便接入已有的Grafana看板。和其它读取服务端状态的接口一样需要read_config权限
+#[get("/v1/metrics")]
+async fn metrics_api(token: web::Data<TokenAuth>, auth: BearerAuth) -> impl Responder {
+  if let Err(err) = validator(&token, &auth, AccessScope::ReadConfig) {
+    return err.response();
+  }
+  HttpResponse::Ok()
+    .content_type("text/plain; version=0.0.4")
+    .body(metrics::encode())
+}
+
+#[derive(serde::Deserialize)]
+struct ResultsQuery {
+  target: Option<String>,
+  #[serde(default)]
+  offset: usize,
+  limit: Option<usize>,
+}
+
+// 分页查看--result-store持久化下来的历史扫描结果
+#[get("/v1/results")]
+async fn results_api(
+  token: web::Data<TokenAuth>,
+  auth: BearerAuth,
+  store: web::Data<Option<Arc<dyn ResultStore>>>,
+  query: web::Query<ResultsQuery>,
+) -> impl Responder {
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return err.response();
+  }
+  let Some(store) = store.as_ref() else {
+    return HttpResponse::NotFound().body("result store is not configured");
+  };
+  let filter = ResultFilter {
+    target: query.target.clone(),
+    offset: query.offset,
+    limit: query.limit,
+  };
+  match store.query(&filter).await {
+    Ok(results) => HttpResponse::Ok().json(results),
+    Err(err) => {
+      error!("{}query results err: {}", Emoji("💢", ""), err);
+      HttpResponse::InternalServerError().finish()
+    }
+  }
+}
+
+// 提交一个异步扫描任务，立即返回任务id，不占住这个请求直到扫描结束
+#[post("/v1/jobs")]
+async fn create_job_api(
+  token: web::Data<TokenAuth>,
+  auth: BearerAuth,
+  config: web::Json<ObserverWardConfig>,
+  cli_config: web::Data<ObserverWardConfig>,
+  queue: web::Data<Arc<JobQueue>>,
+) -> impl Responder {
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return err.response();
+  }
+  let mut config = config.clone();
+  if config.plugin.is_some() {
+    config.plugin = cli_config.plugin.clone();
+  }
+  config.config_dir = cli_config.config_dir.clone();
+  config.mode = cli_config.mode.clone();
+  config.proxy = cli_config.proxy.clone();
+  config.nuclei_args = cli_config.nuclei_args.clone();
+  let target = config.target.clone();
+  let job = queue.enqueue(target, config);
+  HttpResponse::Ok().json(job)
+}
+
+#[get("/v1/jobs/{id}")]
+async fn get_job_api(
+  token: web::Data<TokenAuth>,
+  auth: BearerAuth,
+  queue: web::Data<Arc<JobQueue>>,
+  id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return err.response();
+  }
+  match queue.get(id.into_inner()) {
+    Some(job) => HttpResponse::Ok().json(job),
+    None => HttpResponse::NotFound().finish(),
+  }
+}
+
+#[get("/v1/jobs")]
+async fn list_jobs_api(
+  token: web::Data<TokenAuth>,
+  auth: BearerAuth,
+  queue: web::Data<Arc<JobQueue>>,
+) -> impl Responder {
+  if let Err(err) = validator(&token, &auth, AccessScope::Scan) {
+    return err.response();
+  }
+  HttpResponse::Ok().json(queue.list())
 }
 
 pub async fn api_server(
@@ -137,12 +361,26 @@ pub async fn api_server(
   info!("{}probes loaded: {}", Emoji("📇", ""), templates.len());
   let cl = cluster_templates(&templates);
   info!("{}optimized probes: {}", Emoji("🚀", ""), cl.count());
-  let cluster_templates = web::Data::new(RwLock::new(cl));
+  metrics::OPTIMIZED_PROBES.set(cl.count() as i64);
+  let cl_arc = Arc::new(RwLock::new(cl));
+  let cluster_templates = web::Data::from(cl_arc.clone());
   let web_config = web::Data::new(config.clone());
+  let result_store: Option<Arc<dyn ResultStore>> = config
+    .result_store
+    .as_deref()
+    .and_then(crate::result_store::from_url);
+  let result_store = web::Data::new(result_store);
+  let job_queue = Arc::new(JobQueue::new(config.config_dir.join("jobs"))?);
+  rt::spawn(jobs::run_worker(job_queue.clone(), cl_arc.clone()));
+  let job_queue = web::Data::new(job_queue);
   let token_auth = web::Data::new(TokenAuth {
-    token: config.token.clone(),
+    tokens: config.tokens.clone(),
   });
-  let token = config.token.clone();
+  // 仅用于启动提示里的curl示例，任取一个已配置的令牌（HashMap没有固定顺序）
+  let token = config
+    .tokens
+    .as_ref()
+    .and_then(|tokens| tokens.keys().next().cloned());
   let http_server = HttpServer::new(move || {
     App::new()
       .wrap(middleware::Logger::default())
@@ -150,9 +388,17 @@ pub async fn api_server(
       .app_data(web_config.clone())
       .app_data(web::JsonConfig::default().limit(40960))
       .app_data(cluster_templates.clone())
+      .app_data(result_store.clone())
+      .app_data(job_queue.clone())
       .service(what_web_api)
+      .service(what_web_ws_api)
       .service(get_config_api)
       .service(set_config_api)
+      .service(metrics_api)
+      .service(results_api)
+      .service(create_job_api)
+      .service(get_job_api)
+      .service(list_jobs_api)
   });
   let (http_server, url) = match &listening_address {
     #[cfg(unix)]
@@ -234,3 +480,37 @@ pub fn background() {
     Emoji("💢", "")
   );
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use actix_web::test;
+
+  #[actix_web::test]
+  async fn get_config_api_never_leaks_token_map() {
+    let mut config = ObserverWardConfig::default();
+    let mut tokens = HashMap::new();
+    tokens.insert("super-secret-token".to_string(), vec![AccessScope::ReadConfig]);
+    config.tokens = Some(tokens);
+
+    let token_auth = web::Data::new(TokenAuth {
+      tokens: config.tokens.clone(),
+    });
+    let web_config = web::Data::new(config);
+    let app = test::init_service(
+      App::new()
+        .app_data(token_auth)
+        .app_data(web_config)
+        .service(get_config_api),
+    )
+    .await;
+    let req = test::TestRequest::get()
+      .uri("/v1/config")
+      .insert_header(("Authorization", "Bearer super-secret-token"))
+      .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(!body.contains("super-secret-token"));
+    assert!(!body.contains("tokens"));
+  }
+}
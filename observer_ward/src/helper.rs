@@ -1,11 +1,24 @@
 use crate::cli::ObserverWardConfig;
+use crate::metrics;
 use console::Emoji;
 use engine::template::Template;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use log::{error, info, warn};
+use sha2::{Digest, Sha256};
 use std::fs::File;
 use std::io::Cursor;
 const OBSERVER_WARD_TARGET: &str = env!("OBSERVER_WARD_TARGET");
 
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+  if s.len() % 2 != 0 {
+    return None;
+  }
+  (0..s.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+    .collect()
+}
+
 pub struct Helper<'a> {
   config: &'a ObserverWardConfig,
 }
@@ -24,6 +37,7 @@ impl<'a> Helper<'a> {
           fingerprint_path
             .to_str()
             .unwrap_or("web_fingerprint_v4.json"),
+          true,
         )
         .await
       {
@@ -41,6 +55,9 @@ impl<'a> Helper<'a> {
             Emoji("🔄", ""),
             ts.len()
           );
+          metrics::UPDATE_RESULT
+            .with_label_values(&["fingerprint", "success"])
+            .inc();
         }
         Err(err) => {
           error!("{}update fingerprint err: {}", Emoji("💢", ""), err);
@@ -50,6 +67,9 @@ impl<'a> Helper<'a> {
             Emoji("⚠️", ""),
             fingerprint_path
           );
+          metrics::UPDATE_RESULT
+            .with_label_values(&["fingerprint", "failure"])
+            .inc();
         }
       }
     }
@@ -63,7 +83,7 @@ impl<'a> Helper<'a> {
         let urls = vec!["https://cns.onedom.com/admin-api/asm/vul-finger-template/export-service-fingerprint-json"];
         for url in urls {
             if let Err(err) = self
-                .download_file_from_github(url, service_path.to_str().unwrap_or("service_fingerprint_v4.json"))
+                .download_file_from_github(url, service_path.to_str().unwrap_or("service_fingerprint_v4.json"), true)
                 .await
             {
                 error!("{}update service fingerprint err: {}", Emoji("", ""), err);
@@ -77,10 +97,16 @@ impl<'a> Helper<'a> {
             match serde_json::from_reader::<File, Vec<Template>>(f) {
                 Ok(ts) => {
                     info!("{}successfully updated {} service fingerprint", Emoji("", ""), ts.len());
+                    metrics::UPDATE_RESULT
+                        .with_label_values(&["service_fingerprint", "success"])
+                        .inc();
                 }
                 Err(err) => {
                     error!("{}update service fingerprint err: {}", Emoji("", ""), err);
                     std::fs::remove_file(&service_path).unwrap_or_default();
+                    metrics::UPDATE_RESULT
+                        .with_label_values(&["service_fingerprint", "failure"])
+                        .inc();
                 }
             }
         }
@@ -90,27 +116,21 @@ impl<'a> Helper<'a> {
     &self,
     download_url: &str,
     filename: &str,
+    check_digest: bool,
   ) -> Result<(), std::io::Error> {
     let mut client_builder = self.config.http_client_builder();
     client_builder = client_builder.redirect(engine::slinger::redirect::Policy::Limit(10));
     let client = client_builder.build().unwrap_or_default();
-    match client.get(download_url).send().await {
-      Ok(response) => match File::create(filename) {
-        Ok(mut f) => {
-          if !response.status_code().is_success() {
-            return Err(std::io::Error::new(
-              std::io::ErrorKind::NotFound,
-              "NotFound",
-            ));
-          }
-          let mut content = Cursor::new(response.body().clone().unwrap_or_default().to_vec());
-          std::io::copy(&mut content, &mut f).unwrap_or_default();
-        }
-        Err(err) => {
-          error!("{}create file: {}", Emoji("💢", ""), err);
-          return Err(err);
+    let content = match client.get(download_url).send().await {
+      Ok(response) => {
+        if !response.status_code().is_success() {
+          return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "NotFound",
+          ));
         }
-      },
+        response.body().clone().unwrap_or_default().to_vec()
+      }
       Err(err) => {
         error!(
           "{}download from github {}, err: {}",
@@ -118,11 +138,106 @@ impl<'a> Helper<'a> {
           download_url,
           err
         );
+        metrics::FAILED_REQUESTS.inc();
         return Err(std::io::Error::new(std::io::ErrorKind::NotFound, err));
       }
+    };
+    if check_digest && !self.config.no_verify {
+      self.verify_digest(download_url, &content).await?;
+    }
+    match File::create(filename) {
+      Ok(mut f) => {
+        let mut cursor = Cursor::new(content);
+        std::io::copy(&mut cursor, &mut f).unwrap_or_default();
+      }
+      Err(err) => {
+        error!("{}create file: {}", Emoji("💢", ""), err);
+        return Err(err);
+      }
+    }
+    Ok(())
+  }
+
+  /// 下载同名的`.sha256`摘要文件并与内容比对，不一致时拒绝落盘；摘要文件缺失时跳过校验
+  async fn verify_digest(&self, download_url: &str, content: &[u8]) -> Result<(), std::io::Error> {
+    let digest_url = format!("{download_url}.sha256");
+    let mut client_builder = self.config.http_client_builder();
+    client_builder = client_builder.redirect(engine::slinger::redirect::Policy::Limit(10));
+    let client = client_builder.build().unwrap_or_default();
+    let expected = match client.get(&digest_url).send().await {
+      Ok(response) if response.status_code().is_success() => {
+        response.body().clone().unwrap_or_default().to_vec()
+      }
+      _ => {
+        // 不是所有下载源都提供旁路的.sha256摘要文件，找不到就跳过校验而不是直接拒绝更新
+        error!(
+          "{}no digest found for {}, skip verification",
+          Emoji("⚠️", ""),
+          download_url
+        );
+        return Ok(());
+      }
+    };
+    let expected = String::from_utf8_lossy(&expected)
+      .split_whitespace()
+      .next()
+      .unwrap_or_default()
+      .to_lowercase();
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    let actual = format!("{:x}", hasher.finalize());
+    if actual != expected {
+      error!(
+        "{}digest mismatch for {}: expected {}, got {}",
+        Emoji("💢", ""),
+        download_url,
+        expected,
+        actual
+      );
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "digest mismatch",
+      ));
     }
     Ok(())
   }
+
+  /// 下载同名的`.sig`分离签名，用`--pubkey`指定的Ed25519公钥校验下载到的二进制
+  async fn verify_signature(
+    &self,
+    download_url: &str,
+    filename: &str,
+    pubkey_hex: &str,
+  ) -> Result<(), std::io::Error> {
+    let sig_url = format!("{download_url}.sig");
+    let mut client_builder = self.config.http_client_builder();
+    client_builder = client_builder.redirect(engine::slinger::redirect::Policy::Limit(10));
+    let client = client_builder.build().unwrap_or_default();
+    let sig_bytes = match client.get(&sig_url).send().await {
+      Ok(response) if response.status_code().is_success() => {
+        response.body().clone().unwrap_or_default().to_vec()
+      }
+      _ => {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          "missing signature",
+        ));
+      }
+    };
+    let signature = Signature::from_slice(&sig_bytes)
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let pubkey_bytes = decode_hex(pubkey_hex)
+      .filter(|b| b.len() == 32)
+      .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid pubkey"))?;
+    let mut pubkey_array = [0u8; 32];
+    pubkey_array.copy_from_slice(&pubkey_bytes);
+    let verifying_key = VerifyingKey::from_bytes(&pubkey_array)
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let content = std::fs::read(filename)?;
+    verifying_key
+      .verify(&content, &signature)
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+  }
   pub async fn update_self(&self) {
     // https://doc.rust-lang.org/reference/conditional-compilation.html
     let mut base_url =
@@ -136,11 +251,36 @@ impl<'a> Helper<'a> {
     };
     base_url.push_str(&download_name);
     let save_filename = format!("update_{download_name}");
+    // 配了--pubkey就用签名校验替代摘要校验；没配的话退化成只做摘要校验，而不是两样都要求
+    let has_pubkey = self.config.pubkey.is_some();
     match self
-      .download_file_from_github(&base_url, &save_filename)
+      .download_file_from_github(&base_url, &save_filename, !has_pubkey)
       .await
     {
       Ok(_) => {
+        if !self.config.no_verify {
+          match &self.config.pubkey {
+            Some(pubkey) => {
+              if let Err(err) = self.verify_signature(&base_url, &save_filename, pubkey).await {
+                error!(
+                  "{}signature verification failed, deleting {}: {}",
+                  Emoji("💢", ""),
+                  save_filename,
+                  err
+                );
+                std::fs::remove_file(&save_filename).unwrap_or_default();
+                return;
+              }
+            }
+            None => {
+              warn!(
+                "{}no --pubkey configured, {} was only checked against a sha256 digest (if one was published), not a signature",
+                Emoji("⚠️", ""),
+                save_filename
+              );
+            }
+          }
+        }
         info!(
           "{} please rename the file {} => {}",
           Emoji("ℹ️", ""),
@@ -163,6 +303,7 @@ impl<'a> Helper<'a> {
       .download_file_from_github(
         "https://cns.onedom.com/admin-api/asm/vul/poc-template/plugins-export",
         plugins_zip_path.to_str().unwrap_or("plugins.zip"),
+        true,
       )
       .await
     {
@@ -183,9 +324,15 @@ impl<'a> Helper<'a> {
               Emoji("ℹ️", ""),
               self.config.config_dir
             );
+            metrics::UPDATE_RESULT
+              .with_label_values(&["plugin", "success"])
+              .inc();
           }
           Err(err) => {
             error!("{}open zip archive err: {}", Emoji("💢", ""), err);
+            metrics::UPDATE_RESULT
+              .with_label_values(&["plugin", "failure"])
+              .inc();
           }
         };
       }
@@ -195,6 +342,9 @@ impl<'a> Helper<'a> {
           "{}Please manually unzip the plugins to the directory",
           Emoji("⚠️", "")
         );
+        metrics::UPDATE_RESULT
+          .with_label_values(&["plugin", "failure"])
+          .inc();
       }
     };
   }
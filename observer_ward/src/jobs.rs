@@ -0,0 +1,220 @@
+use crate::cli::ObserverWardConfig;
+use crate::metrics;
+use crate::MatchedResult;
+use crate::ObserverWard;
+use console::Emoji;
+use engine::execute::ClusterType;
+use futures::StreamExt;
+use futures::channel::mpsc::unbounded;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+  Pending,
+  Running,
+  Done,
+  Failed,
+}
+
+/// 一个持久化到磁盘的异步扫描任务，POST /v1/jobs创建，GET /v1/jobs/{id}查询
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Job {
+  pub id: Uuid,
+  pub target: Vec<String>,
+  pub config: ObserverWardConfig,
+  pub state: JobState,
+  pub progress: usize,
+  pub results: Vec<BTreeMap<String, MatchedResult>>,
+}
+
+impl Job {
+  fn new(id: Uuid, target: Vec<String>, config: ObserverWardConfig) -> Self {
+    Self {
+      id,
+      target,
+      config,
+      state: JobState::Pending,
+      progress: 0,
+      results: Vec::new(),
+    }
+  }
+}
+
+/// 落盘在`<config_dir>/jobs/<id>.json`的任务队列，daemon重启后会把未跑完的任务重新排队
+pub struct JobQueue {
+  dir: PathBuf,
+  jobs: RwLock<BTreeMap<Uuid, Job>>,
+  pending: Mutex<VecDeque<Uuid>>,
+}
+
+impl JobQueue {
+  pub fn new(dir: PathBuf) -> std::io::Result<Self> {
+    std::fs::create_dir_all(&dir)?;
+    let mut jobs = BTreeMap::new();
+    let mut pending = VecDeque::new();
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+      for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+          continue;
+        }
+        if let Ok(content) = std::fs::read_to_string(&path) {
+          if let Ok(mut job) = serde_json::from_str::<Job>(&content) {
+            // 崩溃或重启前没跑完的任务重新排队，而不是直接丢弃；旧的结果已经不完整，清空后从头跑
+            if job.state == JobState::Running {
+              job.state = JobState::Pending;
+              job.results.clear();
+              job.progress = 0;
+            }
+            if job.state == JobState::Pending {
+              pending.push_back(job.id);
+            }
+            jobs.insert(job.id, job);
+          }
+        }
+      }
+    }
+    Ok(Self {
+      dir,
+      jobs: RwLock::new(jobs),
+      pending: Mutex::new(pending),
+    })
+  }
+
+  fn persist(&self, job: &Job) {
+    let path = self.dir.join(format!("{}.json", job.id));
+    match serde_json::to_vec_pretty(job) {
+      Ok(bytes) => {
+        if let Err(err) = std::fs::write(path, bytes) {
+          error!("{}persist job {} err: {}", Emoji("💢", ""), job.id, err);
+        }
+      }
+      Err(err) => error!("{}serialize job {} err: {}", Emoji("💢", ""), job.id, err),
+    }
+  }
+
+  pub fn enqueue(&self, target: Vec<String>, config: ObserverWardConfig) -> Job {
+    let job = Job::new(Uuid::new_v4(), target, config);
+    self.persist(&job);
+    // 先让worker能在jobs表里查到这个id，再把它交给pending队列，避免pop出一个还查不到的job
+    self
+      .jobs
+      .write()
+      .unwrap_or_else(|p| p.into_inner())
+      .insert(job.id, job.clone());
+    self.pending.lock().unwrap_or_else(|p| p.into_inner()).push_back(job.id);
+    job
+  }
+
+  pub fn get(&self, id: Uuid) -> Option<Job> {
+    self.jobs.read().unwrap_or_else(|p| p.into_inner()).get(&id).cloned()
+  }
+
+  pub fn list(&self) -> Vec<Job> {
+    self.jobs.read().unwrap_or_else(|p| p.into_inner()).values().cloned().collect()
+  }
+
+  fn next_pending(&self) -> Option<Uuid> {
+    self.pending.lock().unwrap_or_else(|p| p.into_inner()).pop_front()
+  }
+
+  fn update<F: FnOnce(&mut Job)>(&self, id: Uuid, f: F) {
+    let mut guard = self.jobs.write().unwrap_or_else(|p| p.into_inner());
+    if let Some(job) = guard.get_mut(&id) {
+      f(job);
+      self.persist(job);
+    }
+  }
+}
+
+/// 后台worker，每次只拉一个任务串行跑，结果边到边写进Job::results
+pub async fn run_worker(queue: std::sync::Arc<JobQueue>, cl: std::sync::Arc<RwLock<ClusterType>>) {
+  loop {
+    let Some(id) = queue.next_pending() else {
+      tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+      continue;
+    };
+    let Some(job) = queue.get(id) else { continue };
+    queue.update(id, |j| j.state = JobState::Running);
+    let cl = {
+      if let Ok(cl_guard) = cl.read() {
+        cl_guard.clone()
+      } else {
+        ClusterType::default()
+      }
+    };
+    let mut config = job.config.clone();
+    config.target = job.target.clone();
+    let (tx, mut rx) = unbounded();
+    let handle = tokio::task::spawn(async move {
+      ObserverWard::new(&config, cl).execute(tx).await;
+    });
+    while let Some(execute_result) = rx.next().await {
+      metrics::TARGETS_SCANNED.inc();
+      queue.update(id, |j| {
+        j.results.push(execute_result.matched);
+        j.progress = j.results.len();
+      });
+    }
+    // execute()所在的task如果panic了就算失败，否则才是真的跑完了
+    let state = if handle.await.is_ok() {
+      JobState::Done
+    } else {
+      JobState::Failed
+    };
+    queue.update(id, |j| j.state = state);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("observer_ward_jobs_test_{}", Uuid::new_v4()))
+  }
+
+  #[test]
+  fn enqueue_is_visible_in_jobs_map_before_it_can_be_popped() {
+    let dir = temp_dir();
+    let queue = JobQueue::new(dir.clone()).unwrap();
+    let job = queue.enqueue(vec!["127.0.0.1".to_string()], ObserverWardConfig::default());
+    let id = queue.next_pending().unwrap();
+    assert_eq!(id, job.id);
+    assert!(queue.get(id).is_some());
+    std::fs::remove_dir_all(&dir).ok();
+  }
+
+  #[test]
+  fn resume_demotes_running_jobs_and_clears_stale_progress() {
+    let dir = temp_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut job = Job::new(
+      Uuid::new_v4(),
+      vec!["127.0.0.1".to_string()],
+      ObserverWardConfig::default(),
+    );
+    job.state = JobState::Running;
+    job.progress = 3;
+    job.results = vec![BTreeMap::new(), BTreeMap::new(), BTreeMap::new()];
+    std::fs::write(
+      dir.join(format!("{}.json", job.id)),
+      serde_json::to_vec(&job).unwrap(),
+    )
+    .unwrap();
+
+    let queue = JobQueue::new(dir.clone()).unwrap();
+    let resumed = queue.get(job.id).unwrap();
+    assert_eq!(resumed.state, JobState::Pending);
+    assert_eq!(resumed.progress, 0);
+    assert!(resumed.results.is_empty());
+    assert_eq!(queue.next_pending(), Some(job.id));
+    std::fs::remove_dir_all(&dir).ok();
+  }
+}
@@ -0,0 +1,87 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+  Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+  TextEncoder,
+};
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// 已扫描的目标总数
+pub static TARGETS_SCANNED: Lazy<IntCounter> = Lazy::new(|| {
+  let counter = IntCounter::new(
+    "observer_ward_targets_scanned_total",
+    "total number of targets scanned",
+  )
+  .unwrap();
+  REGISTRY.register(Box::new(counter.clone())).unwrap();
+  counter
+});
+
+/// 按指纹名称统计的命中数
+pub static MATCHES_BY_FINGERPRINT: Lazy<IntCounterVec> = Lazy::new(|| {
+  let counter = IntCounterVec::new(
+    Opts::new("observer_ward_matches_total", "matches found per fingerprint name"),
+    &["name"],
+  )
+  .unwrap();
+  REGISTRY.register(Box::new(counter.clone())).unwrap();
+  counter
+});
+
+/// 一次扫描请求的耗时分布
+pub static SCAN_DURATION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+  let histogram = Histogram::with_opts(HistogramOpts::new(
+    "observer_ward_scan_duration_seconds",
+    "scan request duration in seconds",
+  ))
+  .unwrap();
+  REGISTRY.register(Box::new(histogram.clone())).unwrap();
+  histogram
+});
+
+/// 下载指纹/插件/更新文件时请求发送失败的次数；返回非成功状态码和扫描中的失败不计入
+pub static FAILED_REQUESTS: Lazy<IntCounter> = Lazy::new(|| {
+  let counter = IntCounter::new(
+    "observer_ward_failed_requests_total",
+    "number of failed requests",
+  )
+  .unwrap();
+  REGISTRY.register(Box::new(counter.clone())).unwrap();
+  counter
+});
+
+/// 当前聚类后的探针数量，对应`cl.count()`
+pub static OPTIMIZED_PROBES: Lazy<IntGauge> = Lazy::new(|| {
+  let gauge = IntGauge::new(
+    "observer_ward_optimized_probes",
+    "current number of optimized probes after clustering",
+  )
+  .unwrap();
+  REGISTRY.register(Box::new(gauge.clone())).unwrap();
+  gauge
+});
+
+/// 指纹/插件更新结果，按种类和成功与否打标签
+pub static UPDATE_RESULT: Lazy<IntCounterVec> = Lazy::new(|| {
+  let counter = IntCounterVec::new(
+    Opts::new(
+      "observer_ward_update_total",
+      "fingerprint/plugin update attempts",
+    ),
+    &["kind", "result"],
+  )
+  .unwrap();
+  REGISTRY.register(Box::new(counter.clone())).unwrap();
+  counter
+});
+
+/// 将所有指标编码为Prometheus文本格式
+pub fn encode() -> String {
+  let metric_families = REGISTRY.gather();
+  let mut buffer = Vec::new();
+  let encoder = TextEncoder::new();
+  encoder
+    .encode(&metric_families, &mut buffer)
+    .unwrap_or_default();
+  String::from_utf8(buffer).unwrap_or_default()
+}
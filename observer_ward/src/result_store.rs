@@ -0,0 +1,316 @@
+use crate::MatchedResult;
+use async_trait::async_trait;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 一次查询历史扫描结果的条件
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ResultFilter {
+  pub target: Option<String>,
+  #[serde(default)]
+  pub offset: usize,
+  pub limit: Option<usize>,
+}
+
+/// 落盘/入库的一条扫描结果
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredResult {
+  pub target: String,
+  pub matched: BTreeMap<String, MatchedResult>,
+  pub timestamp: u64,
+}
+
+/// `/v1/results`不带`limit`时的默认分页大小，三种存储后端保持一致
+const DEFAULT_QUERY_LIMIT: usize = 50;
+
+fn now_unix() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or_default()
+}
+
+/// 历史扫描结果的存储后端，`file://`、`sqlite://`、`redis://`三种URL各对应一种实现
+#[async_trait]
+pub trait ResultStore: Send + Sync {
+  async fn save(&self, target: &str, result: &BTreeMap<String, MatchedResult>) -> std::io::Result<()>;
+  async fn query(&self, filter: &ResultFilter) -> std::io::Result<Vec<StoredResult>>;
+}
+
+/// 根据`--result-store`配置的URL构造对应的存储后端
+pub fn from_url(url: &str) -> Option<Arc<dyn ResultStore>> {
+  if let Some(path) = url.strip_prefix("file://") {
+    return Some(Arc::new(FileResultStore::new(PathBuf::from(path))));
+  }
+  if let Some(path) = url.strip_prefix("sqlite://") {
+    return match SqliteResultStore::new(path) {
+      Ok(store) => Some(Arc::new(store)),
+      Err(err) => {
+        error!("{}open sqlite result store err: {}", console::Emoji("💢", ""), err);
+        None
+      }
+    };
+  }
+  if url.starts_with("redis://") || url.starts_with("rediss://") {
+    return Some(Arc::new(RedisResultStore::new(url.to_string())));
+  }
+  error!("{}unsupported result store url: {}", console::Emoji("💢", ""), url);
+  None
+}
+
+/// 按JSON Lines追加写入的文件存储，每行一条`StoredResult`
+pub struct FileResultStore {
+  path: PathBuf,
+  lock: Mutex<()>,
+}
+
+impl FileResultStore {
+  pub fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      lock: Mutex::new(()),
+    }
+  }
+}
+
+#[async_trait]
+impl ResultStore for FileResultStore {
+  async fn save(&self, target: &str, result: &BTreeMap<String, MatchedResult>) -> std::io::Result<()> {
+    let record = StoredResult {
+      target: target.to_string(),
+      matched: result.clone(),
+      timestamp: now_unix(),
+    };
+    let line = serde_json::to_string(&record)?;
+    let _guard = self.lock.lock().unwrap_or_else(|p| p.into_inner());
+    let mut f = std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(&self.path)?;
+    writeln!(f, "{line}")
+  }
+
+  async fn query(&self, filter: &ResultFilter) -> std::io::Result<Vec<StoredResult>> {
+    let content = std::fs::read_to_string(&self.path).unwrap_or_default();
+    // 文件是追加写入的，最新的结果在最后；倒序遍历让分页语义和sqlite/redis两个后端保持一致
+    let records: Vec<StoredResult> = content
+      .lines()
+      .rev()
+      .filter_map(|line| serde_json::from_str::<StoredResult>(line).ok())
+      .filter(|r| match &filter.target {
+        Some(target) => &r.target == target,
+        None => true,
+      })
+      .skip(filter.offset)
+      .take(filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+      .collect();
+    Ok(records)
+  }
+}
+
+/// 基于SQLite的存储，适合单机持久化和简单的条件查询
+pub struct SqliteResultStore {
+  conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteResultStore {
+  pub fn new(path: &str) -> rusqlite::Result<Self> {
+    let conn = rusqlite::Connection::open(path)?;
+    conn.execute(
+      "CREATE TABLE IF NOT EXISTS results (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        target TEXT NOT NULL,
+        matched TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+      )",
+      [],
+    )?;
+    Ok(Self {
+      conn: Mutex::new(conn),
+    })
+  }
+}
+
+#[async_trait]
+impl ResultStore for SqliteResultStore {
+  async fn save(&self, target: &str, result: &BTreeMap<String, MatchedResult>) -> std::io::Result<()> {
+    let matched = serde_json::to_string(result)?;
+    let conn = self.conn.lock().unwrap_or_else(|p| p.into_inner());
+    conn
+      .execute(
+        "INSERT INTO results (target, matched, timestamp) VALUES (?1, ?2, ?3)",
+        rusqlite::params![target, matched, now_unix()],
+      )
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    Ok(())
+  }
+
+  async fn query(&self, filter: &ResultFilter) -> std::io::Result<Vec<StoredResult>> {
+    let conn = self.conn.lock().unwrap_or_else(|p| p.into_inner());
+    let limit = filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT) as i64;
+    let mut stmt = conn
+      .prepare(
+        "SELECT target, matched, timestamp FROM results
+         WHERE (?1 IS NULL OR target = ?1)
+         ORDER BY id DESC LIMIT ?2 OFFSET ?3",
+      )
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let rows = stmt
+      .query_map(
+        rusqlite::params![filter.target, limit, filter.offset as i64],
+        |row| {
+          let target: String = row.get(0)?;
+          let matched: String = row.get(1)?;
+          let timestamp: u64 = row.get(2)?;
+          Ok((target, matched, timestamp))
+        },
+      )
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let mut results = Vec::new();
+    for row in rows {
+      let (target, matched, timestamp) = row.map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+      let matched = serde_json::from_str(&matched)?;
+      results.push(StoredResult {
+        target,
+        matched,
+        timestamp,
+      });
+    }
+    Ok(results)
+  }
+}
+
+/// 基于Redis的存储，用多实例共享一份扫描历史，适合分布式部署
+pub struct RedisResultStore {
+  url: String,
+}
+
+impl RedisResultStore {
+  const KEY: &'static str = "observer_ward:results";
+
+  pub fn new(url: String) -> Self {
+    Self { url }
+  }
+
+  async fn connection(&self) -> std::io::Result<redis::aio::MultiplexedConnection> {
+    let client = redis::Client::open(self.url.as_str())
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    client
+      .get_multiplexed_async_connection()
+      .await
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+  }
+}
+
+#[async_trait]
+impl ResultStore for RedisResultStore {
+  async fn save(&self, target: &str, result: &BTreeMap<String, MatchedResult>) -> std::io::Result<()> {
+    use redis::AsyncCommands;
+    let record = StoredResult {
+      target: target.to_string(),
+      matched: result.clone(),
+      timestamp: now_unix(),
+    };
+    let line = serde_json::to_string(&record)?;
+    let mut conn = self.connection().await?;
+    conn
+      .rpush::<_, _, ()>(Self::KEY, line)
+      .await
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+  }
+
+  async fn query(&self, filter: &ResultFilter) -> std::io::Result<Vec<StoredResult>> {
+    use redis::AsyncCommands;
+    let mut conn = self.connection().await?;
+    let lines: Vec<String> = conn
+      .lrange(Self::KEY, 0, -1)
+      .await
+      .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+    let records: Vec<StoredResult> = lines
+      .iter()
+      .rev()
+      .filter_map(|line| serde_json::from_str::<StoredResult>(line).ok())
+      .filter(|r| match &filter.target {
+        Some(target) => &r.target == target,
+        None => true,
+      })
+      .skip(filter.offset)
+      .take(filter.limit.unwrap_or(DEFAULT_QUERY_LIMIT))
+      .collect();
+    Ok(records)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}_{}", name, uuid::Uuid::new_v4()))
+  }
+
+  #[tokio::test]
+  async fn file_store_round_trips_and_filters_by_target() {
+    let path = temp_path("observer_ward_result_store_test");
+    let store = FileResultStore::new(path.clone());
+    store.save("a.com", &BTreeMap::new()).await.unwrap();
+    store.save("b.com", &BTreeMap::new()).await.unwrap();
+
+    let all = store.query(&ResultFilter::default()).await.unwrap();
+    assert_eq!(all.len(), 2);
+
+    let filtered = store
+      .query(&ResultFilter {
+        target: Some("a.com".to_string()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].target, "a.com");
+
+    std::fs::remove_file(&path).ok();
+  }
+
+  #[tokio::test]
+  async fn file_store_orders_newest_first() {
+    let path = temp_path("observer_ward_result_store_test_order");
+    let store = FileResultStore::new(path.clone());
+    store.save("first.com", &BTreeMap::new()).await.unwrap();
+    store.save("second.com", &BTreeMap::new()).await.unwrap();
+
+    let results = store.query(&ResultFilter::default()).await.unwrap();
+    assert_eq!(results[0].target, "second.com");
+    assert_eq!(results[1].target, "first.com");
+
+    std::fs::remove_file(&path).ok();
+  }
+
+  #[tokio::test]
+  async fn sqlite_store_round_trips_and_filters_by_target() {
+    let path = temp_path("observer_ward_result_store_test_sqlite");
+    let store = SqliteResultStore::new(path.to_str().unwrap()).unwrap();
+    store.save("a.com", &BTreeMap::new()).await.unwrap();
+    store.save("b.com", &BTreeMap::new()).await.unwrap();
+
+    let all = store.query(&ResultFilter::default()).await.unwrap();
+    assert_eq!(all.len(), 2);
+
+    let filtered = store
+      .query(&ResultFilter {
+        target: Some("a.com".to_string()),
+        ..Default::default()
+      })
+      .await
+      .unwrap();
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].target, "a.com");
+
+    std::fs::remove_file(&path).ok();
+  }
+}
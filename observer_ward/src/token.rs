@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// 令牌可以被授予的能力范围
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessScope {
+  /// 发起扫描任务
+  Scan,
+  /// 读取服务端配置
+  ReadConfig,
+  /// 修改服务端配置，重新加载指纹或聚类模板
+  WriteConfig,
+  /// 触发指纹/插件的更新
+  Update,
+}